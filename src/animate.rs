@@ -0,0 +1,174 @@
+use std::error::Error;
+use std::io::Cursor;
+
+use chrono::{DateTime, Utc};
+use gif::{Encoder as GifEncoder, Frame, Repeat};
+use serde::Deserialize;
+
+use crate::db::{GetHistoryOptions, LogoState};
+use crate::store::{self, LogoStore};
+
+// Cap the number of frames we'll ever assemble into one animation so a
+// `?limit=` -less request against a long-lived timeline can't blow up memory.
+const MAX_FRAMES: usize = 300;
+
+const DEFAULT_FPS: f32 = 2.0;
+
+#[derive(Debug, Deserialize, Copy, Clone, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum AnimationFormat {
+    Gif,
+    Apng,
+}
+
+impl Default for AnimationFormat {
+    fn default() -> Self {
+        AnimationFormat::Gif
+    }
+}
+
+impl AnimationFormat {
+    pub fn content_type(self) -> &'static str {
+        match self {
+            AnimationFormat::Gif => "image/gif",
+            AnimationFormat::Apng => "image/apng",
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Copy, Clone, Default)]
+pub struct AnimationOptions {
+    #[serde(flatten)]
+    pub history: GetHistoryOptions,
+    #[serde(default)]
+    pub format: AnimationFormat,
+    pub fps: Option<f32>,
+}
+
+struct DecodedFrame {
+    width: u32,
+    height: u32,
+    rgba: Vec<u8>,
+    delay_centis: u16,
+}
+
+pub fn render_history_animation(options: AnimationOptions) -> Result<Vec<u8>, Box<dyn Error>> {
+    // An explicit `?limit=` narrows the window further, but never past
+    // MAX_FRAMES: fetch only the bounded recent slice we'll actually use
+    // instead of loading the whole matching history just to discard most of
+    // it in `decode_frames`.
+    let frame_limit = options
+        .history
+        .limit
+        .map_or(MAX_FRAMES as u32, |limit| limit.min(MAX_FRAMES as u32));
+    let rows = store::store()?.list_recent(options.history, frame_limit)?;
+
+    let frames = decode_frames(rows, options.fps)?;
+    if frames.is_empty() {
+        return Err("no frames found for the requested range".into());
+    }
+
+    match options.format {
+        AnimationFormat::Gif => encode_gif(&frames),
+        AnimationFormat::Apng => encode_apng(&frames),
+    }
+}
+
+fn decode_frames(
+    rows: Vec<LogoState>,
+    fps: Option<f32>,
+) -> Result<Vec<DecodedFrame>, Box<dyn Error>> {
+    // `rows` is already the bounded, chronologically-ordered slice we want
+    // (see `render_history_animation`), so nothing to trim here.
+    let mut first_dims: Option<(u32, u32)> = None;
+    let mut decoded = Vec::with_capacity(rows.len());
+    let mut timestamps: Vec<DateTime<Utc>> = Vec::with_capacity(rows.len());
+
+    for row in rows {
+        let decoder = png::Decoder::new(Cursor::new(&row.logo));
+        let (info, mut reader) = decoder.read_info()?;
+
+        let (width, height) = first_dims.get_or_insert((info.width, info.height));
+        if info.width != *width || info.height != *height {
+            // Skip frames whose dimensions differ from the first rather than
+            // padding them, to keep the decode path simple.
+            continue;
+        }
+
+        let mut rgba = vec![0; info.buffer_size()];
+        reader.next_frame(&mut rgba)?;
+
+        timestamps.push(row.time);
+        decoded.push(DecodedFrame {
+            width: *width,
+            height: *height,
+            rgba,
+            delay_centis: 0,
+        });
+    }
+
+    let fixed_delay_centis = fps.map(|fps| (100.0 / fps.max(0.1)) as u16);
+
+    for (index, frame) in decoded.iter_mut().enumerate() {
+        frame.delay_centis = match fixed_delay_centis {
+            Some(delay) => delay,
+            None => delay_from_timestamps(&timestamps, index),
+        };
+    }
+
+    Ok(decoded)
+}
+
+fn delay_from_timestamps(timestamps: &[DateTime<Utc>], index: usize) -> u16 {
+    let gap = timestamps
+        .get(index + 1)
+        .and_then(|next| (*next - timestamps[index]).to_std().ok());
+
+    match gap {
+        Some(gap) => ((gap.as_millis() / 10).min(u16::MAX as u128)) as u16,
+        // Last frame (or a non-monotonic gap): fall back to the default rate.
+        None => (100.0 / DEFAULT_FPS) as u16,
+    }
+}
+
+fn encode_gif(frames: &[DecodedFrame]) -> Result<Vec<u8>, Box<dyn Error>> {
+    let (width, height) = (frames[0].width as u16, frames[0].height as u16);
+
+    let mut result = Vec::new();
+    {
+        let mut encoder = GifEncoder::new(&mut result, width, height, &[])?;
+        encoder.set_repeat(Repeat::Infinite)?;
+
+        for frame in frames {
+            // `Frame::from_rgba_speed` quantizes via NeuQuant internally.
+            let mut rgba = frame.rgba.clone();
+            let mut gif_frame =
+                Frame::from_rgba_speed(frame.width as u16, frame.height as u16, &mut rgba, 10);
+            gif_frame.delay = frame.delay_centis;
+            encoder.write_frame(&gif_frame)?;
+        }
+    }
+
+    Ok(result)
+}
+
+fn encode_apng(frames: &[DecodedFrame]) -> Result<Vec<u8>, Box<dyn Error>> {
+    let (width, height) = (frames[0].width, frames[0].height);
+
+    let mut result = Vec::new();
+    {
+        let mut encoder = png::Encoder::new(&mut result, width, height);
+        encoder.set_color(png::ColorType::RGBA);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.set_animated(frames.len() as u32, 0)?;
+
+        let mut writer = encoder.write_header()?;
+
+        for frame in frames {
+            writer.set_frame_delay(frame.delay_centis, 100)?;
+            writer.write_image_data(&frame.rgba)?;
+        }
+    }
+
+    Ok(result)
+}
@@ -0,0 +1,56 @@
+use std::env;
+use std::io;
+
+use serde::Deserialize;
+use snafu::{ResultExt, Snafu};
+
+const CONFIG_PATH_ENV: &str = "LOGO_PNG_CONFIG";
+const DEFAULT_CONFIG_PATH: &str = "config.toml";
+
+#[derive(Debug, Deserialize)]
+pub struct ConfigFile {
+    pub store: Store,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type")]
+pub enum Store {
+    Postgres {
+        url: String,
+    },
+    ObjectStorage {
+        endpoint: String,
+        bucket: String,
+        access_key: String,
+        secret_key: String,
+    },
+}
+
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display("Could not read config file {}: {}", path, source))]
+    Read { path: String, source: io::Error },
+    #[snafu(display("Could not parse config file {}: {}", path, source))]
+    Parse {
+        path: String,
+        source: toml::de::Error,
+    },
+}
+
+impl Error {
+    /// Whether this is just "no config file is present" (so callers can fall
+    /// back to the `DATABASE_URL`-only Postgres setup), as opposed to a file
+    /// that exists but is broken, which should be reported rather than
+    /// masked behind a confusing fallback error.
+    pub fn is_not_found(&self) -> bool {
+        matches!(self, Error::Read { source, .. } if source.kind() == io::ErrorKind::NotFound)
+    }
+}
+
+/// Reads the config file pointed at by `LOGO_PNG_CONFIG` (default
+/// `config.toml`).
+pub fn load() -> Result<ConfigFile, Error> {
+    let path = env::var(CONFIG_PATH_ENV).unwrap_or_else(|_| DEFAULT_CONFIG_PATH.to_owned());
+    let contents = std::fs::read_to_string(&path).context(Read { path: path.clone() })?;
+    toml::from_str(&contents).context(Parse { path })
+}
@@ -1,22 +1,26 @@
 use std::env;
+use std::error::Error;
 
-use base64;
 use chrono::{DateTime, Utc};
-use flate2::{write::GzEncoder, Compression};
-use postgres::{Connection, TlsMode};
+use postgres::TlsMode;
+use r2d2::{Pool, PooledConnection};
+use r2d2_postgres::PostgresConnectionManager;
 use serde::{Deserialize, Serialize, Serializer};
-use serde_json;
 use snafu::{ResultExt, Snafu};
-use warp::{
-    http::{self, Response},
-    reply,
-};
+
+use crate::logo::LogoOptions;
+use crate::store::LogoStore;
+
+const DEFAULT_POOL_SIZE: u32 = 10;
 
 #[derive(Serialize)]
 pub struct LogoState {
-    time: DateTime<Utc>,
+    pub(crate) time: DateTime<Utc>,
+    pub(crate) width: u32,
+    pub(crate) height: u32,
+    pub(crate) options: LogoOptions,
     #[serde(serialize_with = "as_base64")]
-    logo: Vec<u8>,
+    pub(crate) logo: Vec<u8>,
 }
 
 fn as_base64<T, S>(key: &T, serializer: S) -> Result<S::Ok, S::Error>
@@ -48,92 +52,225 @@ pub enum Error {
         query: String,
         source: postgres::Error,
     },
-    JsonError {
-        source: serde_json::Error,
-    },
-    HttpError {
-        source: http::Error,
-    },
-    EncodeError {
-        source: std::io::Error,
+    #[snafu(display("Could not get a pooled Postgres connection: {}", source))]
+    PoolError {
+        source: r2d2::Error,
     },
 }
 
-fn get_conn() -> Result<Connection, Error> {
-    let db = std::env::var("DATABASE_URL").context(EnvVar {
-        env: "DATABASE_URL",
-    })?;
-    Ok(Connection::connect(db, TlsMode::None).context(PgError)?)
+#[derive(Debug, Deserialize, Copy, Clone, Default)]
+pub struct GetHistoryOptions {
+    pub(crate) from: Option<DateTime<Utc>>,
+    pub(crate) to: Option<DateTime<Utc>>,
+    pub(crate) limit: Option<u32>,
 }
 
-pub fn init_db() -> Result<(), Error> {
-    let conn = get_conn()?;
+/// `LogoStore` backed by the `timeline` table in Postgres. This is the
+/// default backend; see `object_storage::ObjectStorageStore` for the
+/// alternative driven by the config file.
+///
+/// Holds an `r2d2` connection pool rather than opening a fresh connection
+/// per call, since `update_logo`'s periodic writes and history reads used to
+/// each pay for a new TCP/TLS handshake.
+pub struct PostgresStore {
+    pool: Pool<PostgresConnectionManager>,
+}
+
+impl PostgresStore {
+    pub fn new(database_url: String) -> Result<Self, Error> {
+        PostgresStore::with_pool_size(database_url, DEFAULT_POOL_SIZE)
+    }
 
-    let trans = conn.transaction().context(PgError)?;
+    pub fn with_pool_size(database_url: String, max_pool_size: u32) -> Result<Self, Error> {
+        let manager =
+            PostgresConnectionManager::new(database_url, TlsMode::None).context(PgError)?;
+        let pool = Pool::builder()
+            .max_size(max_pool_size)
+            .build(manager)
+            .context(PoolError)?;
+
+        Ok(PostgresStore { pool })
+    }
 
-    trans
-        .execute(
-            "CREATE TABLE IF NOT EXISTS timeline (
-            created_at TIMESTAMPTZ NOT NULL DEFAULT NOW() PRIMARY KEY,
-            image_png BYTEA NOT NULL
-        )",
-            &[],
-        )
-        .context(PgError)?;
+    /// Builds a store from `DATABASE_URL` (and optionally `DATABASE_POOL_SIZE`),
+    /// for the common case where no config file is provided.
+    pub fn from_env() -> Result<Self, Error> {
+        let database_url = env::var("DATABASE_URL").context(EnvVar {
+            env: "DATABASE_URL",
+        })?;
+        let max_pool_size = env::var("DATABASE_POOL_SIZE")
+            .ok()
+            .and_then(|size| size.parse().ok())
+            .unwrap_or(DEFAULT_POOL_SIZE);
 
-    trans.commit().context(PgError)?;
+        PostgresStore::with_pool_size(database_url, max_pool_size)
+    }
 
-    Ok(())
+    fn get_conn(&self) -> Result<PooledConnection<PostgresConnectionManager>, Error> {
+        self.pool.get().context(PoolError)
+    }
 }
 
-pub fn save_logo(logo_png: &[u8]) -> Result<(), Error> {
-    let conn = get_conn()?;
+impl LogoStore for PostgresStore {
+    fn init(&self) -> Result<(), Box<dyn Error>> {
+        let conn = self.get_conn()?;
 
-    let trans = conn.transaction().context(PgError)?;
+        let trans = conn.transaction().context(PgError)?;
 
-    trans
-        .execute("INSERT INTO timeline (image_png) VALUES ($1)", &[&logo_png])
-        .context(PgError)?;
+        trans
+            .execute(
+                "CREATE TABLE IF NOT EXISTS timeline (
+                created_at TIMESTAMPTZ NOT NULL DEFAULT NOW() PRIMARY KEY,
+                image_png BYTEA NOT NULL,
+                width INTEGER,
+                height INTEGER,
+                render_options JSONB
+            )",
+                &[],
+            )
+            .context(PgError)?;
 
-    trans.commit().context(PgError)?;
+        // Migrate tables created before the width/height/render_options columns existed.
+        for statement in &[
+            "ALTER TABLE timeline ADD COLUMN IF NOT EXISTS width INTEGER",
+            "ALTER TABLE timeline ADD COLUMN IF NOT EXISTS height INTEGER",
+            "ALTER TABLE timeline ADD COLUMN IF NOT EXISTS render_options JSONB",
+        ] {
+            trans.execute(statement, &[]).context(PgError)?;
+        }
 
-    Ok(())
-}
+        trans.commit().context(PgError)?;
 
-#[derive(Debug, Deserialize, Copy, Clone, Default)]
-pub struct GetHistoryOptions {
-    limit: Option<u32>,
-}
+        Ok(())
+    }
+
+    fn save(
+        &self,
+        logo_png: &[u8],
+        width: u32,
+        height: u32,
+        options: &LogoOptions,
+    ) -> Result<(), Box<dyn Error>> {
+        let render_options = serde_json::to_value(options)?;
+
+        let conn = self.get_conn()?;
+
+        let trans = conn.transaction().context(PgError)?;
+
+        trans
+            .execute(
+                "INSERT INTO timeline (image_png, width, height, render_options) VALUES ($1, $2, $3, $4)",
+                &[&logo_png, &(width as i32), &(height as i32), &render_options],
+            )
+            .context(PgError)?;
 
-pub fn get_history(options: GetHistoryOptions) -> Result<reply::Response, Error> {
-    let mut query_str = "SELECT created_at, image_png FROM timeline ORDER BY created_at".to_owned();
-    if let Some(limit) = options.limit {
+        trans.commit().context(PgError)?;
+
+        Ok(())
+    }
+
+    fn list(&self, options: GetHistoryOptions) -> Result<Vec<LogoState>, Box<dyn Error>> {
+        let mut query_str = select_timeline();
+        let clauses = range_clauses(options.from, options.to, None);
+        push_where(&mut query_str, &clauses);
+        query_str.push_str(" ORDER BY created_at");
+        if let Some(limit) = options.limit {
+            // NOTE: This is safe because we know that limit is a number
+            query_str.push_str(&format!(" LIMIT {}", limit));
+        }
+
+        let conn = self.get_conn()?;
+        let res = conn.query(&query_str, &[]).context(PgError)?;
+
+        res.into_iter().map(row_to_state).collect()
+    }
+
+    fn list_page(
+        &self,
+        options: GetHistoryOptions,
+        after: Option<DateTime<Utc>>,
+        limit: u32,
+    ) -> Result<Vec<LogoState>, Box<dyn Error>> {
+        let mut query_str = select_timeline();
+        let clauses = range_clauses(options.from, options.to, after);
+        push_where(&mut query_str, &clauses);
         // NOTE: This is safe because we know that limit is a number
-        query_str.push_str(&format!(" LIMIT {}", limit));
+        query_str.push_str(&format!(" ORDER BY created_at LIMIT {}", limit));
+
+        let conn = self.get_conn()?;
+        let res = conn.query(&query_str, &[]).context(PgError)?;
+
+        res.into_iter().map(row_to_state).collect()
     }
 
-    let conn = get_conn()?;
-    let res = conn.query(&query_str, &[]).context(PgError)?;
+    fn list_recent(
+        &self,
+        options: GetHistoryOptions,
+        limit: u32,
+    ) -> Result<Vec<LogoState>, Box<dyn Error>> {
+        let mut query_str = select_timeline();
+        let clauses = range_clauses(options.from, options.to, None);
+        push_where(&mut query_str, &clauses);
+        // NOTE: This is safe because we know that limit is a number
+        query_str.push_str(&format!(" ORDER BY created_at DESC LIMIT {}", limit));
+
+        let conn = self.get_conn()?;
+        let res = conn.query(&query_str, &[]).context(PgError)?;
 
-    let data = res
-        .into_iter()
-        .map(|row| LogoState {
-            time: row.get(0),
-            logo: row.get(1),
-        })
-        .collect::<Vec<_>>();
+        let mut rows: Vec<LogoState> = res
+            .into_iter()
+            .map(row_to_state)
+            .collect::<Result<_, _>>()?;
+        // Fetched newest-first to let the DB do the trimming; flip back to
+        // chronological order for callers (e.g. animation playback).
+        rows.reverse();
 
-    // TODO: Check if the browser accept gzip
-    // let result = serde_json::to_vec(&data).context(JsonError)?;
+        Ok(rows)
+    }
+}
+
+fn select_timeline() -> String {
+    "SELECT created_at, image_png, width, height, render_options FROM timeline".to_owned()
+}
 
-    let mut encoder = GzEncoder::new(Vec::new(), Compression::fast());
-    serde_json::to_writer(&mut encoder, &data).context(JsonError)?;
+fn range_clauses(
+    from: Option<DateTime<Utc>>,
+    to: Option<DateTime<Utc>>,
+    after: Option<DateTime<Utc>>,
+) -> Vec<String> {
+    let mut clauses = Vec::new();
+    if let Some(from) = from {
+        // NOTE: This is safe because an RFC 3339 timestamp never contains quotes
+        clauses.push(format!("created_at >= '{}'", from.to_rfc3339()));
+    }
+    if let Some(to) = to {
+        clauses.push(format!("created_at <= '{}'", to.to_rfc3339()));
+    }
+    if let Some(after) = after {
+        clauses.push(format!("created_at > '{}'", after.to_rfc3339()));
+    }
+    clauses
+}
+
+fn push_where(query_str: &mut String, clauses: &[String]) {
+    if !clauses.is_empty() {
+        query_str.push_str(" WHERE ");
+        query_str.push_str(&clauses.join(" AND "));
+    }
+}
 
-    let result = encoder.finish().context(EncodeError)?;
+fn row_to_state(row: postgres::rows::Row) -> Result<LogoState, Box<dyn Error>> {
+    let render_options: Option<serde_json::Value> = row.get(4);
+    let options = render_options
+        .map(|value| serde_json::from_value(value))
+        .transpose()?
+        .unwrap_or_default();
 
-    Ok(Response::builder()
-        .header("Content-Type", "application/json")
-        .header("Content-Encoding", "gzip")
-        .body(result.into())
-        .context(HttpError)?)
+    Ok(LogoState {
+        time: row.get(0),
+        logo: row.get(1),
+        width: row.get::<_, Option<i32>>(2).unwrap_or(0) as u32,
+        height: row.get::<_, Option<i32>>(3).unwrap_or(0) as u32,
+        options,
+    })
 }
@@ -1,23 +1,71 @@
 use std::error::Error;
+use std::io::Cursor;
 use std::mem;
 
+use image::{imageops::FilterType, DynamicImage, ImageBuffer, ImageOutputFormat};
 use lazy_static::lazy_static;
 use parking_lot::RwLock;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
-use crate::{db, live};
+use crate::live;
+use crate::store::{self, LogoStore};
 
 lazy_static! {
     // Last logo fetched from the api
     static ref LOGO_CACHE: RwLock<LogoResponse> = RwLock::new(LogoResponse { logo: vec![] });
 }
 
-#[derive(Debug, Deserialize, Copy, Clone, Default)]
+#[derive(Debug, Deserialize, Serialize, Copy, Clone, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    Png,
+    Jpeg,
+    Webp,
+    Gif,
+    Bmp,
+}
+
+impl Default for Format {
+    fn default() -> Self {
+        Format::Png
+    }
+}
+
+impl Format {
+    pub fn content_type(self) -> &'static str {
+        match self {
+            Format::Png => "image/png",
+            Format::Jpeg => "image/jpeg",
+            Format::Webp => "image/webp",
+            Format::Gif => "image/gif",
+            Format::Bmp => "image/bmp",
+        }
+    }
+
+    fn output_format(self) -> ImageOutputFormat {
+        match self {
+            Format::Png => ImageOutputFormat::Png,
+            Format::Jpeg => ImageOutputFormat::Jpeg(90),
+            Format::Webp => ImageOutputFormat::WebP,
+            Format::Gif => ImageOutputFormat::Gif,
+            Format::Bmp => ImageOutputFormat::Bmp,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Copy, Clone, Default)]
 pub struct LogoOptions {
     size: Option<u32>,
     character: Option<usize>,
     #[serde(default)]
     crop: bool,
+    #[serde(default)]
+    format: Format,
+    width: Option<u32>,
+    height: Option<u32>,
+    // Lanczos3 for smooth scaling instead of the default nearest-neighbour (crisp pixels)
+    #[serde(default)]
+    smooth: bool,
 }
 
 #[derive(Debug, Deserialize, Eq, PartialEq)]
@@ -45,29 +93,126 @@ pub fn update_logo() -> Result<(), Box<dyn Error>> {
         // Avoid deadlock
         drop(logo_cache);
 
-        let logo_png = get_logo_png(LogoOptions::default())?;
+        let options = LogoOptions::default();
+        let logo = get_logo_data(options)?;
+        let (width, height) = (logo.width as u32, logo.height as u32);
+        let logo_png = encode_logo(logo, options)?;
 
         live::send_update(&logo_png);
-        if let Err(err) = db::save_logo(&logo_png) {
-            eprintln!("Error saving logo to db: {}", err);
+        match store::store().and_then(|store| store.save(&logo_png, width, height, &options)) {
+            Ok(()) => {}
+            Err(err) => eprintln!("Error saving logo to store: {}", err),
         }
     }
 
     Ok(())
 }
 pub fn get_logo_png(options: LogoOptions) -> Result<Vec<u8>, Box<dyn Error>> {
-    let mut result = Vec::new();
     let logo = get_logo_data(options)?; // An array containing a RGBA sequence. First pixel is red and second pixel is black.
+    encode_logo(logo, options)
+}
+
+pub const SVG_CONTENT_TYPE: &str = "image/svg+xml";
+
+/// Renders the same `character`/`crop` selection as `get_logo_png`, but as an
+/// SVG: one `<rect>` per logical pixel (merging horizontally adjacent
+/// same-color runs), in a `viewBox` sized to the logical grid. Scales to any
+/// size without the integer `size` multiplier, so `size` is ignored here.
+pub fn get_logo_svg(options: LogoOptions) -> Result<String, Box<dyn Error>> {
+    let mut options = options;
+    options.size = Some(1);
+
+    let logo = get_logo_data(options)?;
+
+    let mut rects = String::new();
+    for y in 0..logo.height {
+        let mut x = 0;
+        while x < logo.width {
+            let pixel = pixel_at(&logo, x, y);
+
+            let mut run_width = 1;
+            while x + run_width < logo.width && pixel_at(&logo, x + run_width, y) == pixel {
+                run_width += 1;
+            }
 
-    {
-        let mut encoder = png::Encoder::new(&mut result, logo.width as u32, logo.height as u32); // Width is 2 pixels and height is 1.
-        encoder.set_color(png::ColorType::RGBA);
-        encoder.set_depth(png::BitDepth::Eight);
-        let mut writer = encoder.write_header().unwrap();
+            // Transparent background pixels (the unwritten canvas from
+            // `get_logo_data`) get no `<rect>` at all, so the SVG keeps a
+            // transparent background instead of a solid block of "#000000".
+            if let Some((r, g, b)) = pixel.rgb() {
+                rects.push_str(&format!(
+                    r#"<rect x="{}" y="{}" width="{}" height="1" fill="#{:02x}{:02x}{:02x}"/>"#,
+                    x, y, run_width, r, g, b
+                ));
+            }
 
-        writer.write_image_data(&logo.data).unwrap(); // Save
+            x += run_width;
+        }
     }
 
+    Ok(format!(
+        r#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 {} {}" shape-rendering="crispEdges">{}</svg>"#,
+        logo.width, logo.height, rects
+    ))
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+struct Pixel {
+    r: u8,
+    g: u8,
+    b: u8,
+    a: u8,
+}
+
+impl Pixel {
+    /// `None` for a fully transparent pixel, so callers can skip drawing it
+    /// instead of rendering the canvas's unwritten background as opaque black.
+    fn rgb(self) -> Option<(u8, u8, u8)> {
+        if self.a == 0 {
+            None
+        } else {
+            Some((self.r, self.g, self.b))
+        }
+    }
+}
+
+fn pixel_at(logo: &Logo, x: usize, y: usize) -> Pixel {
+    let idx = (x + y * logo.width) * 4;
+    Pixel {
+        r: logo.data[idx],
+        g: logo.data[idx + 1],
+        b: logo.data[idx + 2],
+        a: logo.data[idx + 3],
+    }
+}
+
+fn encode_logo(logo: Logo, options: LogoOptions) -> Result<Vec<u8>, Box<dyn Error>> {
+    let image: DynamicImage = DynamicImage::ImageRgba8(
+        ImageBuffer::from_raw(logo.width as u32, logo.height as u32, logo.data)
+            .ok_or("rendered logo buffer did not match its declared dimensions")?,
+    );
+
+    let image = match (options.width, options.height) {
+        (None, None) => image,
+        (width, height) => {
+            let filter = if options.smooth {
+                FilterType::Lanczos3
+            } else {
+                FilterType::Nearest
+            };
+            match (width, height) {
+                // Only one dimension given: preserve the logo's aspect ratio
+                // instead of stretching it to the original's other dimension.
+                (Some(width), None) => image.resize(width, u32::MAX, filter),
+                (None, Some(height)) => image.resize(u32::MAX, height, filter),
+                (Some(width), Some(height)) => image.resize_exact(width, height, filter),
+                (None, None) => unreachable!(),
+            }
+        }
+    };
+
+    let mut result = Vec::new();
+    image.write_to(&mut Cursor::new(&mut result), options.format.output_format())?;
+
     Ok(result)
 }
 
@@ -0,0 +1,181 @@
+use std::error::Error;
+
+use chrono::{DateTime, Utc};
+use parking_lot::Mutex;
+use s3::bucket::Bucket;
+use s3::creds::Credentials;
+use s3::region::Region;
+use serde::{Deserialize, Serialize};
+
+use crate::db::{GetHistoryOptions, LogoState};
+use crate::logo::LogoOptions;
+use crate::store::LogoStore;
+
+const INDEX_KEY: &str = "index.json";
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct FrameMeta {
+    time: DateTime<Utc>,
+    width: u32,
+    height: u32,
+    options: LogoOptions,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+struct Index {
+    frames: Vec<FrameMeta>,
+}
+
+/// `LogoStore` backed by an S3-compatible object store (e.g. MinIO). Each
+/// frame is written as its own object keyed by timestamp; `index.json`
+/// tracks which timestamps exist so `list` doesn't need a bucket listing.
+pub struct ObjectStorageStore {
+    bucket: Bucket,
+    // Guards read-modify-write of the index object against concurrent saves.
+    index_lock: Mutex<()>,
+}
+
+impl ObjectStorageStore {
+    pub fn new(
+        endpoint: String,
+        bucket: String,
+        access_key: String,
+        secret_key: String,
+    ) -> Result<Self, Box<dyn Error>> {
+        let credentials = Credentials::new(Some(&access_key), Some(&secret_key), None, None, None)?;
+        let region = Region::Custom {
+            region: String::new(),
+            endpoint,
+        };
+        let bucket = Bucket::new(&bucket, region, credentials)?;
+
+        Ok(ObjectStorageStore {
+            bucket,
+            index_lock: Mutex::new(()),
+        })
+    }
+
+    fn frame_key(time: DateTime<Utc>) -> String {
+        format!("frames/{}.png", time.to_rfc3339())
+    }
+
+    /// `None` means the index object doesn't exist yet (a fresh bucket), as
+    /// opposed to an `Err`, which is a real fetch error -- callers need to
+    /// tell those apart rather than silently treating a transient failure as
+    /// "no index".
+    fn read_index(&self) -> Result<Option<Index>, Box<dyn Error>> {
+        match self.bucket.get_object(INDEX_KEY) {
+            Ok((data, 200)) => Ok(Some(serde_json::from_slice(&data)?)),
+            Ok((_, 404)) => Ok(None),
+            Ok((_, status)) => Err(format!("fetching {} returned status {}", INDEX_KEY, status).into()),
+            Err(err) => Err(Box::new(err)),
+        }
+    }
+
+    fn write_index(&self, index: &Index) -> Result<(), Box<dyn Error>> {
+        let data = serde_json::to_vec(index)?;
+        self.bucket.put_object(INDEX_KEY, &data)?;
+        Ok(())
+    }
+
+    /// Frame metadata only (no frame bytes fetched yet), filtered by `filter`
+    /// and, for pagination, restricted to frames strictly after `after`, in
+    /// ascending order.
+    fn matching_frames(
+        &self,
+        filter: GetHistoryOptions,
+        after: Option<DateTime<Utc>>,
+    ) -> Result<Vec<FrameMeta>, Box<dyn Error>> {
+        let index = self.read_index()?.unwrap_or_default();
+
+        let mut frames: Vec<FrameMeta> = index
+            .frames
+            .into_iter()
+            .filter(|frame| filter.from.map_or(true, |from| frame.time >= from))
+            .filter(|frame| filter.to.map_or(true, |to| frame.time <= to))
+            .filter(|frame| after.map_or(true, |after| frame.time > after))
+            .collect();
+        frames.sort_by_key(|frame| frame.time);
+        Ok(frames)
+    }
+
+    fn fetch_frames(&self, frames: Vec<FrameMeta>) -> Result<Vec<LogoState>, Box<dyn Error>> {
+        frames
+            .into_iter()
+            .map(|frame| {
+                let (data, _) = self.bucket.get_object(&Self::frame_key(frame.time))?;
+                Ok(LogoState {
+                    time: frame.time,
+                    logo: data,
+                    width: frame.width,
+                    height: frame.height,
+                    options: frame.options,
+                })
+            })
+            .collect()
+    }
+}
+
+impl LogoStore for ObjectStorageStore {
+    fn init(&self) -> Result<(), Box<dyn Error>> {
+        let _guard = self.index_lock.lock();
+        if self.read_index()?.is_none() {
+            self.write_index(&Index::default())?;
+        }
+        Ok(())
+    }
+
+    fn save(
+        &self,
+        logo_png: &[u8],
+        width: u32,
+        height: u32,
+        options: &LogoOptions,
+    ) -> Result<(), Box<dyn Error>> {
+        let time = Utc::now();
+        self.bucket.put_object(&Self::frame_key(time), logo_png)?;
+
+        let _guard = self.index_lock.lock();
+        let mut index = self.read_index()?.unwrap_or_default();
+        index.frames.push(FrameMeta {
+            time,
+            width,
+            height,
+            options: *options,
+        });
+        self.write_index(&index)
+    }
+
+    fn list(&self, filter: GetHistoryOptions) -> Result<Vec<LogoState>, Box<dyn Error>> {
+        let mut frames = self.matching_frames(filter, None)?;
+        if let Some(limit) = filter.limit {
+            frames.truncate(limit as usize);
+        }
+        self.fetch_frames(frames)
+    }
+
+    fn list_page(
+        &self,
+        filter: GetHistoryOptions,
+        after: Option<DateTime<Utc>>,
+        limit: u32,
+    ) -> Result<Vec<LogoState>, Box<dyn Error>> {
+        let mut frames = self.matching_frames(filter, after)?;
+        frames.truncate(limit as usize);
+        self.fetch_frames(frames)
+    }
+
+    fn list_recent(
+        &self,
+        filter: GetHistoryOptions,
+        limit: u32,
+    ) -> Result<Vec<LogoState>, Box<dyn Error>> {
+        // `matching_frames` only touches the (small) index, not frame bytes,
+        // so trimming to the tail here -- before `fetch_frames` -- means we
+        // only ever download the frames we're actually going to return.
+        let mut frames = self.matching_frames(filter, None)?;
+        let skip = frames.len().saturating_sub(limit as usize);
+        frames.drain(..skip);
+        self.fetch_frames(frames)
+    }
+}
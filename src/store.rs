@@ -0,0 +1,262 @@
+use std::collections::VecDeque;
+use std::error::Error;
+use std::sync::Arc;
+
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use flate2::{write::GzEncoder, Compression};
+use futures::stream;
+use lazy_static::lazy_static;
+use parking_lot::Mutex;
+use warp::{http::Response, hyper, reply};
+
+use crate::config::{self, Store as StoreConfig};
+use crate::db::{GetHistoryOptions, LogoState, PostgresStore};
+use crate::logo::LogoOptions;
+use crate::object_storage::ObjectStorageStore;
+
+// Rows are fetched from the store this many at a time, so a history request
+// over a long timeline keeps memory flat instead of holding every matching
+// row at once.
+const STREAM_PAGE_SIZE: u32 = 50;
+
+lazy_static! {
+    // `None` until the first successful build. Unlike holding the store
+    // itself in a `lazy_static`, a build failure here isn't cached: the next
+    // call to `store()` retries instead of every future call panicking.
+    static ref STORE_CELL: Mutex<Option<Arc<dyn LogoStore>>> = Mutex::new(None);
+}
+
+/// Where rendered logo frames get persisted and listed back from. Keeps the
+/// warp handlers and `update_logo` from caring whether that's Postgres or an
+/// object-storage bucket.
+pub trait LogoStore: Send + Sync {
+    fn init(&self) -> Result<(), Box<dyn Error>>;
+    fn save(
+        &self,
+        logo_png: &[u8],
+        width: u32,
+        height: u32,
+        options: &LogoOptions,
+    ) -> Result<(), Box<dyn Error>>;
+    fn list(&self, filter: GetHistoryOptions) -> Result<Vec<LogoState>, Box<dyn Error>>;
+    /// Fetches up to `limit` rows strictly after `after` (keyset pagination
+    /// on `time`, ascending), for callers that want to walk a large history
+    /// without holding every matching row in memory at once.
+    fn list_page(
+        &self,
+        filter: GetHistoryOptions,
+        after: Option<DateTime<Utc>>,
+        limit: u32,
+    ) -> Result<Vec<LogoState>, Box<dyn Error>>;
+    /// Fetches only the most recent `limit` rows matching `filter`, returned
+    /// in ascending (chronological) order, for callers (like animation
+    /// rendering) that only ever want a bounded recent window and shouldn't
+    /// have to load the entire matching history to get it.
+    fn list_recent(
+        &self,
+        filter: GetHistoryOptions,
+        limit: u32,
+    ) -> Result<Vec<LogoState>, Box<dyn Error>>;
+}
+
+/// Returns the configured store, building (and pooling) it on first use.
+/// The lock is only held long enough to fetch or build the `Arc`, not while
+/// the store is actually used, so callers don't serialize on each other.
+pub fn store() -> Result<Arc<dyn LogoStore>, Box<dyn Error>> {
+    let mut cell = STORE_CELL.lock();
+    if let Some(store) = cell.as_ref() {
+        return Ok(Arc::clone(store));
+    }
+
+    let store: Arc<dyn LogoStore> = Arc::from(build_store()?);
+    *cell = Some(Arc::clone(&store));
+    Ok(store)
+}
+
+fn build_store() -> Result<Box<dyn LogoStore>, Box<dyn Error>> {
+    match config::load() {
+        Ok(config) => build_store_from_config(&config.store),
+        // No config file at all: fall back to the `DATABASE_URL`-only setup.
+        // A config file that's present but broken is a real error and should
+        // be reported, not masked behind a confusing `DATABASE_URL must be
+        // set` message.
+        Err(err) if err.is_not_found() => Ok(Box::new(PostgresStore::from_env()?)),
+        Err(err) => Err(Box::new(err)),
+    }
+}
+
+fn build_store_from_config(config: &StoreConfig) -> Result<Box<dyn LogoStore>, Box<dyn Error>> {
+    Ok(match config {
+        StoreConfig::Postgres { url } => Box::new(PostgresStore::new(url.clone())?),
+        StoreConfig::ObjectStorage {
+            endpoint,
+            bucket,
+            access_key,
+            secret_key,
+        } => Box::new(ObjectStorageStore::new(
+            endpoint.clone(),
+            bucket.clone(),
+            access_key.clone(),
+            secret_key.clone(),
+        )?),
+    })
+}
+
+/// Serves the timeline history, negotiating `Accept-Encoding` rather than
+/// always gzipping, and streaming rows as they're read from the store (via
+/// `LogoStore::list_page`, one page at a time) instead of buffering the
+/// whole result set in memory before sending anything.
+pub fn get_history(
+    options: GetHistoryOptions,
+    accept_encoding: Option<String>,
+) -> Result<reply::Response, Box<dyn Error>> {
+    let store = store()?;
+    let gzip = accept_encoding
+        .map(|header| header.split(',').any(|enc| enc.trim().starts_with("gzip")))
+        .unwrap_or(false);
+
+    let mut builder = Response::builder().header("Content-Type", "application/json");
+    if gzip {
+        builder = builder.header("Content-Encoding", "gzip");
+    }
+
+    let state = RowStreamState {
+        store,
+        filter: options,
+        after: None,
+        remaining: options.limit,
+        pending: VecDeque::new(),
+        first: true,
+        done: false,
+        gzip_encoder: if gzip {
+            Some(GzEncoder::new(Vec::new(), Compression::fast()))
+        } else {
+            None
+        },
+    };
+
+    Ok(builder.body(hyper::Body::wrap_stream(stream::unfold(state, next_chunk)))?)
+}
+
+struct RowStreamState {
+    store: Arc<dyn LogoStore>,
+    filter: GetHistoryOptions,
+    after: Option<DateTime<Utc>>,
+    // `None` means no `?limit=` was given, i.e. unbounded.
+    remaining: Option<u32>,
+    pending: VecDeque<LogoState>,
+    // Whether the opening `[` still needs to be emitted.
+    first: bool,
+    // The store is known to have no more rows matching `filter`/`after`, or
+    // `remaining` has been exhausted.
+    done: bool,
+    // `Some` for the life of the stream when gzipping; held here (rather than
+    // spun up fresh per chunk) so the whole response is one gzip member that
+    // benefits from cross-row dictionary reuse instead of ~20 bytes of
+    // header/trailer overhead on every single row.
+    gzip_encoder: Option<GzEncoder<Vec<u8>>>,
+}
+
+/// Drives the response body one JSON array element at a time, refilling
+/// `pending` from `LogoStore::list_page` a page at a time (not the whole
+/// history at once) whenever it runs dry.
+async fn next_chunk(
+    mut state: RowStreamState,
+) -> Option<(Result<Bytes, std::io::Error>, RowStreamState)> {
+    if state.pending.is_empty() && !state.done {
+        let page_limit = match state.remaining {
+            Some(remaining) => remaining.min(STREAM_PAGE_SIZE),
+            None => STREAM_PAGE_SIZE,
+        };
+
+        if page_limit == 0 {
+            state.done = true;
+        } else {
+            // Ask for one more row than we mean to emit this page. A page
+            // that comes back *short* of `page_limit` is a reliable "no more
+            // data" signal; a page that comes back exactly `page_limit` long
+            // is not, since the true row count may land exactly on a page
+            // boundary (a history of exactly 50, 100, 150, ... rows) and the
+            // next page would come back empty. Peeking one row ahead avoids
+            // ending the stream (and the JSON array) without ever popping
+            // that last "it was actually the end" lookup.
+            match state
+                .store
+                .list_page(state.filter, state.after, page_limit + 1)
+            {
+                Ok(mut rows) => {
+                    if (rows.len() as u32) <= page_limit {
+                        state.done = true;
+                    } else {
+                        rows.truncate(page_limit as usize);
+                    }
+                    if let Some(last) = rows.last() {
+                        state.after = Some(last.time);
+                    }
+                    if let Some(remaining) = state.remaining.as_mut() {
+                        *remaining = remaining.saturating_sub(rows.len() as u32);
+                        if *remaining == 0 {
+                            state.done = true;
+                        }
+                    }
+                    state.pending.extend(rows);
+                }
+                Err(err) => {
+                    state.done = true;
+                    return Some((
+                        Err(std::io::Error::new(std::io::ErrorKind::Other, err.to_string())),
+                        state,
+                    ));
+                }
+            }
+        }
+    }
+
+    let row = state.pending.pop_front();
+    let is_first = std::mem::replace(&mut state.first, false);
+
+    let (chunk, is_last) = match row {
+        Some(row) => {
+            let is_last = state.pending.is_empty() && state.done;
+
+            let mut chunk = Vec::new();
+            chunk.push(if is_first { b'[' } else { b',' });
+            serde_json::to_writer(&mut chunk, &row).expect("LogoState always serializes");
+            if is_last {
+                chunk.push(b']');
+            }
+            (chunk, is_last)
+        }
+        // Nothing was ever emitted: a `?limit=0` or a genuinely empty history.
+        None if is_first => (b"[]".to_vec(), true),
+        None => return None,
+    };
+
+    let result = encode_chunk(&mut state.gzip_encoder, chunk, is_last);
+    Some((result, state))
+}
+
+/// Writes `data` through `encoder` (a no-op passthrough when not gzipping).
+/// On the last chunk of the stream, consumes the encoder to `finish()` it so
+/// the gzip member's trailing CRC32/size footer is written exactly once, at
+/// the end of the whole response rather than per chunk.
+fn encode_chunk(
+    encoder: &mut Option<GzEncoder<Vec<u8>>>,
+    data: Vec<u8>,
+    is_last: bool,
+) -> Result<Bytes, std::io::Error> {
+    match encoder {
+        None => Ok(Bytes::from(data)),
+        Some(_) if is_last => {
+            let mut encoder = encoder.take().expect("checked Some above");
+            std::io::Write::write_all(&mut encoder, &data)?;
+            Ok(Bytes::from(encoder.finish()?))
+        }
+        Some(encoder) => {
+            std::io::Write::write_all(encoder, &data)?;
+            std::io::Write::flush(encoder)?;
+            Ok(Bytes::from(std::mem::take(encoder.get_mut())))
+        }
+    }
+}